@@ -0,0 +1,21 @@
+/// An Aggregate is an entity which State is composed of one or more
+/// Value-Objects, Entities or Aggregates.
+///
+/// State mutations are expressed through clear Domain Events which, if
+/// applied in the same order as they happened chronologically, will yield
+/// the same Aggregate State.
+pub trait Aggregate {
+    /// State of the Aggregate.
+    type State;
+
+    /// Domain events that express mutations of the Aggregate State.
+    type Event;
+
+    /// Error type returned in `apply` when mutating the Aggregate State
+    /// to the next version fails.
+    type Error;
+
+    /// Applies the changes described by the domain event in `Self::Event`
+    /// to the current `state` of the `Aggregate`.
+    fn apply(state: Self::State, event: Self::Event) -> Result<Self::State, Self::Error>;
+}
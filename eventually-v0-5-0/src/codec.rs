@@ -0,0 +1,385 @@
+//! Pluggable codecs for event payloads, and a [`CodecStore`] decorator
+//! that applies one transparently around any [`event::Store`].
+//!
+//! The main use case is at-rest encryption: wrap a plain serialization
+//! codec in an [`EncryptedEventCodec`] and decorate an in-memory, sled or
+//! SQL store with [`CodecStore`], and payloads are encrypted before
+//! they're handed to the inner store and decrypted after being read back,
+//! without the inner store ever seeing plaintext.
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use rand::RngCore;
+
+use crate::{
+    event::{self, Events, Persisted, Store as _},
+    version::Version,
+};
+
+/// Encodes/decodes an event's payload to/from its storage representation.
+///
+/// Implementations are free to compress, encrypt, or otherwise transform
+/// the payload, as long as `decode` can reverse whatever `encode` did.
+pub trait EventCodec<Evt> {
+    /// Error returned when `decode` fails, e.g. because the payload was
+    /// corrupted or encoded with an incompatible codec.
+    type Error;
+
+    /// Encodes `event` into its storage representation.
+    fn encode(&self, event: &Evt) -> Vec<u8>;
+
+    /// Decodes `bytes` back into an event, failing if they were never
+    /// produced by a compatible `encode`.
+    fn decode(&self, bytes: &[u8]) -> Result<Evt, Self::Error>;
+}
+
+/// A plain [`EventCodec`] that serializes events with `bincode`, applying
+/// no compression or encryption. The usual choice to pass as the `inner`
+/// codec to [`EncryptedEventCodec`].
+#[derive(Clone)]
+pub struct BincodeEventCodec<Evt>(PhantomData<Evt>);
+
+impl<Evt> Default for BincodeEventCodec<Evt> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<Evt> EventCodec<Evt> for BincodeEventCodec<Evt>
+where
+    Evt: serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Error = bincode::Error;
+
+    fn encode(&self, event: &Evt) -> Vec<u8> {
+        bincode::serialize(event).expect("an event should always serialize")
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Evt, Self::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// Identifies which key (and implicitly which generation of the AEAD
+/// scheme) encrypted a given payload.
+pub type KeyId = u16;
+
+const NONCE_LEN: usize = 12;
+
+/// Error returned by [`EncryptedEventCodec::decode`].
+#[derive(Debug, thiserror::Error)]
+pub enum DecryptError<InnerError> {
+    #[error("encrypted payload is too short to contain a valid envelope")]
+    Truncated,
+    #[error("payload was encrypted under unknown key id {0}")]
+    UnknownKey(KeyId),
+    #[error("AEAD decryption failed, the payload may have been corrupted or tampered with")]
+    Decryption,
+    #[error(transparent)]
+    Inner(InnerError),
+}
+
+/// An [`EventCodec`] decorator that AEAD-encrypts whatever bytes the
+/// wrapped `inner` codec produces, and decrypts them back before handing
+/// them to `inner::decode`.
+///
+/// Each payload is stored as `key_id (2 bytes) || nonce (12 bytes) ||
+/// ciphertext`. `key_id` records which of the `keys` encrypted it, so
+/// rotating to a new key only means adding an entry and changing
+/// `current_key_id` -- events already encrypted under an older key keep
+/// decoding as long as that key is still present in `keys`.
+#[derive(Clone)]
+pub struct EncryptedEventCodec<C> {
+    inner: C,
+    keys: HashMap<KeyId, Aes256Gcm>,
+    current_key_id: KeyId,
+}
+
+impl<C> EncryptedEventCodec<C> {
+    /// Wraps `inner`, encrypting new payloads under `current_key_id`.
+    /// `keys` must contain `current_key_id`, plus any older key still
+    /// needed to decode payloads written before a rotation.
+    pub fn new(inner: C, keys: HashMap<KeyId, [u8; 32]>, current_key_id: KeyId) -> Self {
+        assert!(
+            keys.contains_key(&current_key_id),
+            "current_key_id must be present in `keys`"
+        );
+
+        let keys = keys
+            .into_iter()
+            .map(|(id, key)| (id, Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+            .collect();
+
+        Self {
+            inner,
+            keys,
+            current_key_id,
+        }
+    }
+}
+
+impl<C, Evt> EventCodec<Evt> for EncryptedEventCodec<C>
+where
+    C: EventCodec<Evt>,
+{
+    type Error = DecryptError<C::Error>;
+
+    fn encode(&self, event: &Evt) -> Vec<u8> {
+        let plaintext = self.inner.encode(event);
+
+        let cipher = self
+            .keys
+            .get(&self.current_key_id)
+            .expect("current_key_id was validated to be present at construction");
+
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = Nonce::from_slice(&nonce_bytes);
+
+        let ciphertext = cipher
+            .encrypt(nonce, plaintext.as_ref())
+            .expect("encrypting an event payload should not fail");
+
+        let mut envelope = Vec::with_capacity(2 + NONCE_LEN + ciphertext.len());
+        envelope.extend_from_slice(&self.current_key_id.to_be_bytes());
+        envelope.extend_from_slice(&nonce_bytes);
+        envelope.extend_from_slice(&ciphertext);
+        envelope
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Evt, Self::Error> {
+        if bytes.len() < 2 + NONCE_LEN {
+            return Err(DecryptError::Truncated);
+        }
+
+        let key_id = KeyId::from_be_bytes([bytes[0], bytes[1]]);
+        let nonce = Nonce::from_slice(&bytes[2..2 + NONCE_LEN]);
+        let ciphertext = &bytes[2 + NONCE_LEN..];
+
+        let cipher = self
+            .keys
+            .get(&key_id)
+            .ok_or(DecryptError::UnknownKey(key_id))?;
+
+        let plaintext = cipher
+            .decrypt(nonce, ciphertext)
+            .map_err(|_| DecryptError::Decryption)?;
+
+        self.inner.decode(&plaintext).map_err(DecryptError::Inner)
+    }
+}
+
+/// Error returned by a [`CodecStore`], wrapping either a failure from the
+/// decorated store or a failure to decode an event payload.
+#[derive(Debug, thiserror::Error)]
+pub enum CodecStoreError<StoreError, CodecError> {
+    #[error(transparent)]
+    Store(StoreError),
+    #[error(transparent)]
+    Codec(CodecError),
+}
+
+/// A [`event::Store`] decorator that transparently encodes event payloads
+/// with a [`EventCodec`] before handing them to `inner`, and decodes them
+/// back on read -- so `inner` only ever sees opaque bytes.
+///
+/// Works with any `event::Store<Event = Vec<u8>>`: the in-memory store
+/// (for tests), or the sled and SQL backends (for production).
+pub struct CodecStore<S, C, Evt> {
+    inner: S,
+    codec: C,
+    _marker: PhantomData<Evt>,
+}
+
+impl<S, C, Evt> CodecStore<S, C, Evt> {
+    pub fn new(inner: S, codec: C) -> Self {
+        Self {
+            inner,
+            codec,
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn decode_persisted<Id, Evt, C, StoreError>(
+    result: Result<Persisted<Id, Vec<u8>>, StoreError>,
+    codec: &C,
+) -> Result<Persisted<Id, Evt>, CodecStoreError<StoreError, C::Error>>
+where
+    C: EventCodec<Evt>,
+{
+    let persisted = result.map_err(CodecStoreError::Store)?;
+    let payload = codec
+        .decode(&persisted.inner.payload)
+        .map_err(CodecStoreError::Codec)?;
+
+    Ok(Persisted {
+        stream_id: persisted.stream_id,
+        version: persisted.version,
+        global_sequence: persisted.global_sequence,
+        inner: event::Event::from(payload),
+    })
+}
+
+#[async_trait]
+impl<S, C, Evt> event::Store for CodecStore<S, C, Evt>
+where
+    S: event::Store<Event = Vec<u8>> + Send + Sync,
+    S::StreamId: Send + Sync,
+    C: EventCodec<Evt> + Clone + Send + Sync + 'static,
+    C::Error: Send,
+    Evt: Send + Sync + 'static,
+{
+    type StreamId = S::StreamId;
+    type Event = Evt;
+    type StreamError = CodecStoreError<S::StreamError, C::Error>;
+    type AppendError = S::AppendError;
+
+    fn stream(
+        &self,
+        id: &Self::StreamId,
+        select: event::VersionSelect,
+    ) -> event::Stream<Self::StreamId, Self::Event, Self::StreamError> {
+        let codec = self.codec.clone();
+
+        self.inner
+            .stream(id, select)
+            .map(move |result| decode_persisted(result, &codec))
+            .boxed()
+    }
+
+    fn stream_all(
+        &self,
+        select: event::VersionSelect,
+    ) -> event::Stream<Self::StreamId, Self::Event, Self::StreamError> {
+        let codec = self.codec.clone();
+
+        self.inner
+            .stream_all(select)
+            .map(move |result| decode_persisted(result, &codec))
+            .boxed()
+    }
+
+    async fn append(
+        &self,
+        id: Self::StreamId,
+        version_check: event::StreamVersionExpected,
+        events: Events<Self::Event>,
+    ) -> Result<Version, Self::AppendError> {
+        let encoded: Events<Vec<u8>> = events
+            .into_iter()
+            .map(|evt| event::Event::from(self.codec.encode(&evt.payload)))
+            .collect();
+
+        self.inner.append(id, version_check, encoded).await
+    }
+
+    async fn remove(&self, id: &Self::StreamId) -> Result<(), Self::AppendError> {
+        self.inner.remove(id).await
+    }
+
+    async fn truncate(
+        &self,
+        id: &Self::StreamId,
+        before: Version,
+    ) -> Result<(), event::TruncateError> {
+        self.inner.truncate(id, before).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::TryStreamExt;
+
+    use super::*;
+    use crate::{event::StreamVersionExpected, test::store::InMemoryEventStore};
+
+    fn test_keys() -> HashMap<KeyId, [u8; 32]> {
+        let mut keys = HashMap::new();
+        keys.insert(1, [7u8; 32]);
+        keys.insert(2, [9u8; 32]);
+        keys
+    }
+
+    #[test]
+    fn encrypted_codec_round_trips_a_payload() {
+        let codec = EncryptedEventCodec::new(
+            BincodeEventCodec::<String>::default(),
+            test_keys(),
+            1,
+        );
+
+        let encoded = codec.encode(&"hello".to_string());
+        let decoded = codec.decode(&encoded).expect("decode should not fail");
+
+        assert_eq!("hello", decoded);
+    }
+
+    #[test]
+    fn encrypted_codec_decodes_payloads_from_an_older_key_after_rotation() {
+        let codec_v1 = EncryptedEventCodec::new(BincodeEventCodec::<String>::default(), test_keys(), 1);
+        let encoded = codec_v1.encode(&"archived".to_string());
+
+        let codec_v2 = EncryptedEventCodec::new(BincodeEventCodec::<String>::default(), test_keys(), 2);
+        let decoded = codec_v2
+            .decode(&encoded)
+            .expect("decoding a payload encrypted under a previous key should still work");
+
+        assert_eq!("archived", decoded);
+    }
+
+    #[test]
+    fn encrypted_codec_rejects_an_unknown_key_id() {
+        let codec = EncryptedEventCodec::new(BincodeEventCodec::<String>::default(), test_keys(), 1);
+        let mut encoded = codec.encode(&"hello".to_string());
+        encoded[0..2].copy_from_slice(&99u16.to_be_bytes());
+
+        let error = codec.decode(&encoded).expect_err("unknown key id should fail");
+        assert!(matches!(error, DecryptError::UnknownKey(99)));
+    }
+
+    #[tokio::test]
+    async fn codec_store_encrypts_before_storing_and_decrypts_on_read() {
+        let inner = InMemoryEventStore::<&'static str, Vec<u8>>::default();
+        let codec = EncryptedEventCodec::new(BincodeEventCodec::<String>::default(), test_keys(), 1);
+        let store = CodecStore::new(inner, codec);
+
+        let stream_id = "stream:test";
+
+        store
+            .append(
+                stream_id,
+                StreamVersionExpected::Exact(Version(0)),
+                vec![event::Event::from("secret-event".to_string())],
+            )
+            .await
+            .expect("append should not fail");
+
+        let stored: Vec<_> = store
+            .inner
+            .stream(&stream_id, event::VersionSelect::All)
+            .try_collect()
+            .await
+            .expect("stream should not fail");
+
+        assert_ne!(
+            BincodeEventCodec::<String>::default().encode(&"secret-event".to_string()),
+            stored[0].inner.payload
+        );
+
+        let events: Vec<_> = store
+            .stream(&stream_id, event::VersionSelect::All)
+            .try_collect()
+            .await
+            .expect("stream should not fail");
+
+        assert_eq!(1, events.len());
+        assert_eq!("secret-event", events[0].inner.payload);
+    }
+}
@@ -0,0 +1,347 @@
+//! Command-handling layer on top of [`Aggregate`]: validates a [`Command`]
+//! against the current state, turns it into events, and persists both the
+//! events and an audit trail of the command that produced them.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::RwLock,
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    aggregate::Aggregate,
+    event::{self, Events, Store as EventStore},
+    repository::{self, Repository},
+    snapshot::SnapshotStore,
+    version::Version,
+};
+
+/// A Command expresses an intent to change an `Aggregate`'s state.
+///
+/// [`CommandHandler::handle`] loads the aggregate, calls `handle` on the
+/// command with its current state, and persists the resulting events
+/// under optimistic concurrency.
+pub trait Command<A: Aggregate> {
+    /// A short, human-readable summary of this command, recorded in the
+    /// stream's command history for auditing. Keep it small: this is not
+    /// meant to carry the full command payload, just enough to tell
+    /// operators what happened.
+    fn summary(&self) -> String;
+
+    /// Validates `self` against the aggregate's current `state` and
+    /// returns the `Event`s it produces, in order.
+    fn handle(self, state: &A::State) -> Result<Vec<A::Event>, A::Error>;
+}
+
+/// A single entry in a stream's command history: which command ran, what
+/// events it produced, and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredCommand {
+    /// Monotonically increasing, per-stream sequence number assigned by
+    /// the [`CommandHistoryStore`] this was recorded in.
+    pub sequence: u64,
+    /// [`Command::summary`] of the command that produced this entry.
+    pub command_summary: String,
+    /// The (first, last) version of the events this command appended, or
+    /// `None` if the command was a no-op that produced no events.
+    pub resulting_event_versions: Option<(Version, Version)>,
+    /// When the command was recorded.
+    pub recorded_at: SystemTime,
+}
+
+/// Filters a [`CommandHistoryStore::query`] call.
+#[derive(Debug, Clone, Default)]
+pub struct CommandHistoryCriteria {
+    /// Only return entries recorded from this sequence onwards.
+    pub since_sequence: Option<u64>,
+}
+
+impl CommandHistoryCriteria {
+    fn matches(&self, command: &StoredCommand) -> bool {
+        self.since_sequence
+            .map_or(true, |since| command.sequence >= since)
+    }
+}
+
+/// Stores a per-stream audit log of [`StoredCommand`]s, so operators can
+/// query which command produced which events.
+#[async_trait]
+pub trait CommandHistoryStore<Id> {
+    /// Error type returned by this store's operations.
+    type Error;
+
+    /// Records a new entry for the stream `id`, assigning it the next
+    /// sequence number, and returns the stored entry.
+    async fn append(
+        &self,
+        id: &Id,
+        command_summary: String,
+        resulting_event_versions: Option<(Version, Version)>,
+    ) -> Result<StoredCommand, Self::Error>;
+
+    /// Returns the entries recorded for the stream `id` matching
+    /// `criteria`, in sequence order.
+    async fn query(
+        &self,
+        id: &Id,
+        criteria: &CommandHistoryCriteria,
+    ) -> Result<Vec<StoredCommand>, Self::Error>;
+}
+
+#[derive(Debug, Default)]
+struct InMemoryCommandHistoryStoreBackend {
+    history: HashMap<String, Vec<StoredCommand>>,
+}
+
+/// An in-memory [`CommandHistoryStore`], mirroring
+/// [`InMemoryEventStore`](crate::test::store::InMemoryEventStore).
+#[derive(Debug, Default)]
+pub struct InMemoryCommandHistoryStore {
+    backend: RwLock<InMemoryCommandHistoryStoreBackend>,
+}
+
+#[async_trait]
+impl<Id> CommandHistoryStore<Id> for InMemoryCommandHistoryStore
+where
+    Id: Display + Send + Sync,
+{
+    type Error = std::convert::Infallible;
+
+    async fn append(
+        &self,
+        id: &Id,
+        command_summary: String,
+        resulting_event_versions: Option<(Version, Version)>,
+    ) -> Result<StoredCommand, Self::Error> {
+        let mut backend = self
+            .backend
+            .write()
+            .expect("acquire write lock on command history backend");
+
+        let entries = backend.history.entry(id.to_string()).or_default();
+
+        let stored_command = StoredCommand {
+            sequence: entries.len() as u64 + 1,
+            command_summary,
+            resulting_event_versions,
+            recorded_at: SystemTime::now(),
+        };
+
+        entries.push(stored_command.clone());
+
+        Ok(stored_command)
+    }
+
+    async fn query(
+        &self,
+        id: &Id,
+        criteria: &CommandHistoryCriteria,
+    ) -> Result<Vec<StoredCommand>, Self::Error> {
+        let backend = self
+            .backend
+            .read()
+            .expect("acquire read lock on command history backend");
+
+        Ok(backend
+            .history
+            .get(&id.to_string())
+            .into_iter()
+            .flatten()
+            .filter(|command| criteria.matches(command))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Error returned by [`CommandHandler::handle`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error<RepositoryError, CommandError, AppendError, HistoryError> {
+    #[error("failed to load the aggregate: {0}")]
+    Repository(RepositoryError),
+    #[error("command was rejected: {0}")]
+    Command(CommandError),
+    #[error("failed to append the resulting events: {0}")]
+    Append(AppendError),
+    #[error("failed to record the command in history: {0}")]
+    History(HistoryError),
+}
+
+/// Loads an `Aggregate` through a [`Repository`], validates a [`Command`]
+/// against it, and persists the resulting events together with a
+/// [`StoredCommand`] audit entry.
+///
+/// Events are appended before the history entry is recorded: they are
+/// the source of truth, so a crash in between leaves an event with no
+/// matching history entry (detectable, and harmless to the aggregate's
+/// state) rather than a history entry for events that were never
+/// actually persisted.
+pub struct CommandHandler<A, S, Snap, H> {
+    repository: Repository<A, S, Snap>,
+    history: H,
+}
+
+impl<A, S, Snap, H> CommandHandler<A, S, Snap, H> {
+    pub fn new(repository: Repository<A, S, Snap>, history: H) -> Self {
+        Self { repository, history }
+    }
+}
+
+impl<A, S, Snap, H> CommandHandler<A, S, Snap, H>
+where
+    A: Aggregate,
+    A::State: Default,
+    A::Event: Clone,
+    S: EventStore<Event = A::Event>,
+    S::StreamId: Display + Clone + Send + Sync,
+    Snap: SnapshotStore<S::StreamId, A::State>,
+    H: CommandHistoryStore<S::StreamId>,
+{
+    /// Loads the aggregate at `id`, validates `command` against its
+    /// current state, and persists the resulting events and history
+    /// entry. Returns the stream version after the append.
+    pub async fn handle<C>(
+        &self,
+        id: &S::StreamId,
+        command: C,
+    ) -> Result<
+        Version,
+        Error<repository::Error<S::StreamError, Snap::Error, A::Error>, A::Error, S::AppendError, H::Error>,
+    >
+    where
+        C: Command<A>,
+    {
+        let (state, expected_version) = self.repository.get(id).await.map_err(Error::Repository)?;
+
+        let summary = command.summary();
+        let new_events = command.handle(&state).map_err(Error::Command)?;
+
+        let mut new_state = state;
+        for event in new_events.iter().cloned() {
+            new_state = A::apply(new_state, event).map_err(Error::Command)?;
+        }
+
+        let produced_events = !new_events.is_empty();
+        let events: Events<A::Event> = new_events.into_iter().map(event::Event::from).collect();
+
+        let new_version = self
+            .repository
+            .save(id, expected_version, events, new_state)
+            .await
+            .map_err(Error::Append)?;
+
+        // A command can legally validate without producing any events
+        // (a no-op); in that case there's no version range to record.
+        let resulting_event_versions = produced_events.then(|| (expected_version + 1, new_version));
+
+        self.history
+            .append(id, summary, resulting_event_versions)
+            .await
+            .map_err(Error::History)?;
+
+        Ok(new_version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::{
+        snapshot::InMemorySnapshotStore,
+        test::{
+            fixtures::{Counter, CounterAggregate},
+            store::InMemoryEventStore,
+        },
+    };
+
+    struct Increment;
+
+    impl Command<CounterAggregate> for Increment {
+        fn summary(&self) -> String {
+            "increment".to_string()
+        }
+
+        fn handle(self, _state: &Counter) -> Result<Vec<&'static str>, Infallible> {
+            Ok(vec!["incremented"])
+        }
+    }
+
+    struct NoOp;
+
+    impl Command<CounterAggregate> for NoOp {
+        fn summary(&self) -> String {
+            "no-op".to_string()
+        }
+
+        fn handle(self, _state: &Counter) -> Result<Vec<&'static str>, Infallible> {
+            Ok(vec![])
+        }
+    }
+
+    fn handler() -> CommandHandler<
+        CounterAggregate,
+        InMemoryEventStore<&'static str, &'static str>,
+        InMemorySnapshotStore<Counter>,
+        InMemoryCommandHistoryStore,
+    > {
+        let repository = Repository::new(
+            InMemoryEventStore::default(),
+            InMemorySnapshotStore::default(),
+            0,
+        );
+
+        CommandHandler::new(repository, InMemoryCommandHistoryStore::default())
+    }
+
+    #[tokio::test]
+    async fn handle_appends_events_and_records_their_version_range() {
+        let handler = handler();
+        let stream_id = "counter:1";
+
+        let new_version = handler
+            .handle(&stream_id, Increment)
+            .await
+            .expect("handle should not fail");
+
+        assert_eq!(Version(1), new_version);
+
+        let history = handler
+            .history
+            .query(&stream_id, &CommandHistoryCriteria::default())
+            .await
+            .expect("query should not fail");
+
+        assert_eq!(1, history.len());
+        assert_eq!("increment", history[0].command_summary);
+        assert_eq!(
+            Some((Version(1), Version(1))),
+            history[0].resulting_event_versions
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_records_no_version_range_for_a_no_op_command() {
+        let handler = handler();
+        let stream_id = "counter:1";
+
+        let new_version = handler
+            .handle(&stream_id, NoOp)
+            .await
+            .expect("handle should not fail");
+
+        assert_eq!(Version(0), new_version);
+
+        let history = handler
+            .history
+            .query(&stream_id, &CommandHistoryCriteria::default())
+            .await
+            .expect("query should not fail");
+
+        assert_eq!(1, history.len());
+        assert_eq!(None, history[0].resulting_event_versions);
+    }
+}
@@ -0,0 +1,151 @@
+//! Core abstractions for recording and reading back Domain Events.
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::version::Version;
+
+/// An event to be appended to an event stream, not yet persisted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Event<T> {
+    pub payload: T,
+}
+
+impl<T> From<T> for Event<T> {
+    fn from(payload: T) -> Self {
+        Self { payload }
+    }
+}
+
+/// A batch of events to be appended through [`Store::append`].
+pub type Events<T> = Vec<Event<T>>;
+
+/// An event that has been persisted to an event stream.
+///
+/// `global_sequence` is a monotonically increasing number assigned by the
+/// `Store` at append time, unique across every stream it manages. It is
+/// what makes [`Store::stream_all`] able to yield events from different
+/// streams in the order they were actually committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Persisted<Id, Evt> {
+    pub stream_id: Id,
+    pub version: Version,
+    pub global_sequence: u64,
+    pub inner: Event<Evt>,
+}
+
+/// The persisted counterpart of [`Events`], as returned when reading a
+/// stream back from a `Store`.
+pub type PersistedEvents<Id, Evt> = Vec<Persisted<Id, Evt>>;
+
+/// A (possibly unbounded) stream of persisted events, as returned by
+/// [`Store::stream`] and [`Store::stream_all`].
+pub type Stream<Id, Evt, Err> = BoxStream<'static, Result<Persisted<Id, Evt>, Err>>;
+
+/// Selects which events of a stream to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSelect {
+    /// Read every event in the stream, from the beginning.
+    All,
+    /// Read only events from the specified [`Version`] onwards.
+    From(Version),
+}
+
+/// The expected version of a stream an [`Store::append`] call is checked
+/// against, implementing optimistic concurrency control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamVersionExpected {
+    /// Skip the concurrency check entirely.
+    Any,
+    /// Fail the append unless the stream is currently at this exact
+    /// version.
+    Exact(Version),
+}
+
+/// Error returned by [`Store::truncate`] when asked to discard events up
+/// to a version the stream hasn't reached yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("cannot truncate stream before version {before}: stream head is only at version {head}")]
+pub struct TruncateError {
+    pub before: Version,
+    pub head: Version,
+}
+
+/// A `Store` is where Domain Events live once they're persisted out of an
+/// in-memory `Aggregate` Repository.
+#[async_trait]
+pub trait Store {
+    /// Id type used to uniquely identify an event stream.
+    type StreamId;
+
+    /// Domain Event type recorded in the streams managed by this `Store`.
+    type Event;
+
+    /// Error type returned when reading a stream fails.
+    type StreamError;
+
+    /// Error type returned when an `append` fails, typically due to a
+    /// stream version conflict.
+    type AppendError;
+
+    /// Opens an event stream for the specified `id`, filtered by `select`.
+    fn stream(
+        &self,
+        id: &Self::StreamId,
+        select: VersionSelect,
+    ) -> Stream<Self::StreamId, Self::Event, Self::StreamError>;
+
+    /// Opens a stream of every event ever appended to this `Store`, across
+    /// all streams, ordered by the global sequence assigned at append
+    /// time -- i.e. in commit order.
+    ///
+    /// This is the foundation catch-up subscriptions and projections are
+    /// built on, since they need to observe every aggregate consistently,
+    /// which a per-stream `stream` cannot provide.
+    fn stream_all(&self, select: VersionSelect) -> Stream<Self::StreamId, Self::Event, Self::StreamError>;
+
+    /// Appends `events` to the stream identified by `id`, checking the
+    /// stream version against `version_check` first.
+    async fn append(
+        &self,
+        id: Self::StreamId,
+        version_check: StreamVersionExpected,
+        events: Events<Self::Event>,
+    ) -> Result<Version, Self::AppendError>;
+
+    /// Removes the stream identified by `id` and all its events.
+    async fn remove(&self, id: &Self::StreamId) -> Result<(), Self::AppendError>;
+
+    /// Discards events from the stream `id` with a version older than
+    /// `before`, e.g. once a snapshot has made them redundant to fold
+    /// from scratch.
+    ///
+    /// # Concurrency invariant
+    ///
+    /// Truncation MUST NOT move the stream's stored last-version used by
+    /// the optimistic `append` check: discarding a prefix of the stream
+    /// does not change what version the stream is currently at.
+    ///
+    /// The default implementation is a no-op; backends that cannot
+    /// reclaim storage without violating the invariant above should leave
+    /// it at that rather than implementing it unsafely.
+    ///
+    /// # A note on error handling
+    ///
+    /// The return type is fixed to [`TruncateError`], which only has room
+    /// for the version check above -- unlike `stream`/`stream_all`
+    /// (`StreamError`) and `append`/`remove` (`AppendError`), there is no
+    /// associated error type for `truncate` to report a backend failure
+    /// (a dropped connection, a corrupted record) through. Persistent
+    /// implementations are expected to `.expect()`/panic on that class of
+    /// failure instead, since they have nowhere typed to put it; callers
+    /// that can't tolerate `truncate` panicking should avoid relying on
+    /// persistent backends reaching this method under failure conditions
+    /// they'd otherwise surface as a typed error anywhere else in this
+    /// trait.
+    async fn truncate(&self, _id: &Self::StreamId, _before: Version) -> Result<(), TruncateError> {
+        Ok(())
+    }
+}
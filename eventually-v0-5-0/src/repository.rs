@@ -0,0 +1,327 @@
+//! Loads and saves [`Aggregate`] state on top of an [`event::Store`],
+//! optionally resuming from a [`SnapshotStore`] instead of folding the
+//! whole stream from version 0.
+
+use std::fmt::Display;
+
+use crate::{
+    aggregate::Aggregate,
+    event::{self, Events, Store as EventStore},
+    snapshot::{should_snapshot, SnapshotRecord, SnapshotStore},
+    version::Version,
+};
+
+use futures::{
+    stream::{iter, StreamExt},
+    TryStreamExt,
+};
+
+/// Error returned by [`Repository::get`] and [`Repository::save`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error<StreamError, SnapshotError, ApplyError> {
+    #[error("failed to read the event stream: {0}")]
+    Stream(StreamError),
+    #[error("failed to read or write a snapshot: {0}")]
+    Snapshot(SnapshotError),
+    #[error("failed to apply an event to the aggregate state: {0}")]
+    Apply(ApplyError),
+}
+
+/// Loads and saves the `State` of an `Aggregate` `A`, backed by an
+/// [`event::Store`] `S` and a [`SnapshotStore`] `Snap`.
+///
+/// `snapshot_every` controls how often [`Repository::save`] writes a new
+/// snapshot: every `snapshot_every` events persisted to a stream. Pass `0`
+/// to disable snapshotting, falling back to folding the whole stream on
+/// every [`Repository::get`].
+pub struct Repository<A, S, Snap> {
+    event_store: S,
+    snapshot_store: Snap,
+    snapshot_every: u64,
+    _marker: std::marker::PhantomData<A>,
+}
+
+impl<A, S, Snap> Repository<A, S, Snap> {
+    pub fn new(event_store: S, snapshot_store: Snap, snapshot_every: u64) -> Self {
+        Self {
+            event_store,
+            snapshot_store,
+            snapshot_every,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<A, S, Snap> Repository<A, S, Snap>
+where
+    A: Aggregate,
+    A::State: Default,
+    S: EventStore<Event = A::Event>,
+    S::StreamId: Display + Clone + Send + Sync,
+    Snap: SnapshotStore<S::StreamId, A::State>,
+{
+    /// Loads the current `State` of the stream `id`, together with the
+    /// stream [`Version`] it was loaded at.
+    ///
+    /// Resumes from the latest snapshot when one is available, folding
+    /// only the events appended since; falls back to folding from version
+    /// 0 when there's no snapshot yet. A snapshot that turns out to be
+    /// [stale](SnapshotRecord::is_stale) -- claiming a version the stream
+    /// never actually reached, e.g. because the event store was reset
+    /// independently of the snapshot store -- is discarded in favor of
+    /// folding the whole stream from scratch.
+    pub async fn get(
+        &self,
+        id: &S::StreamId,
+    ) -> Result<(A::State, Version), Error<S::StreamError, Snap::Error, A::Error>> {
+        let (mut state, mut version, mut events) = match self.snapshot_store.get(id).await.map_err(Error::Snapshot)? {
+            Some((state, snapshot_version)) => {
+                let tail: Vec<_> = self
+                    .event_store
+                    .stream(id, event::VersionSelect::From(snapshot_version + 1))
+                    .try_collect()
+                    .await
+                    .map_err(Error::Stream)?;
+
+                if tail.is_empty() {
+                    // The snapshot claims to be fully caught up with the
+                    // stream. `tail` already confirmed no events exist
+                    // past `snapshot_version`, so a single probe for the
+                    // event *at* that version tells us whether the
+                    // snapshot is still current, or stale -- e.g.
+                    // claiming a version the event store never reached
+                    // because it was reset independently of the
+                    // snapshot store.
+                    let last_event_version = self
+                        .event_store
+                        .stream(id, event::VersionSelect::From(snapshot_version))
+                        .try_next()
+                        .await
+                        .map_err(Error::Stream)?
+                        .map_or(Version::default(), |persisted| persisted.version);
+
+                    let record = SnapshotRecord {
+                        state,
+                        snapshot_version,
+                        last_event_version,
+                    };
+
+                    if record.is_stale() {
+                        let full: Vec<_> = self
+                            .event_store
+                            .stream(id, event::VersionSelect::All)
+                            .try_collect()
+                            .await
+                            .map_err(Error::Stream)?;
+
+                        (A::State::default(), Version::default(), iter(full).map(Ok).boxed())
+                    } else {
+                        let no_tail: Vec<event::Persisted<S::StreamId, A::Event>> = Vec::new();
+                        (record.state, snapshot_version, iter(no_tail).map(Ok).boxed())
+                    }
+                } else {
+                    (state, snapshot_version, iter(tail).map(Ok).boxed())
+                }
+            }
+            None => (
+                A::State::default(),
+                Version::default(),
+                self.event_store
+                    .stream(id, event::VersionSelect::From(Version::default() + 1)),
+            ),
+        };
+
+        while let Some(persisted) = events.try_next().await.map_err(Error::Stream)? {
+            state = A::apply(state, persisted.inner.payload).map_err(Error::Apply)?;
+            version = persisted.version;
+        }
+
+        Ok((state, version))
+    }
+
+    /// Appends `events` to the stream `id`, checking it's still at
+    /// `expected_version`, and writes a new snapshot of `new_state` if
+    /// the resulting version crosses a `snapshot_every` boundary.
+    ///
+    /// `new_state` must be the `State` obtained by folding `events` on
+    /// top of whatever [`Repository::get`] returned.
+    pub async fn save(
+        &self,
+        id: &S::StreamId,
+        expected_version: Version,
+        events: Events<A::Event>,
+        new_state: A::State,
+    ) -> Result<Version, S::AppendError> {
+        let new_version = self
+            .event_store
+            .append(
+                id.clone(),
+                event::StreamVersionExpected::Exact(expected_version),
+                events,
+            )
+            .await?;
+
+        if should_snapshot(expected_version, new_version, self.snapshot_every) {
+            // A failed snapshot write doesn't invalidate the events that
+            // were just durably appended; the next `get` simply folds a
+            // longer tail from the last (older, still valid) snapshot.
+            let _ = self.snapshot_store.save(id, new_state, new_version).await;
+        }
+
+        Ok(new_version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        snapshot::InMemorySnapshotStore,
+        test::{
+            fixtures::{Counter, CounterAggregate},
+            store::InMemoryEventStore,
+        },
+    };
+
+    fn repository() -> Repository<
+        CounterAggregate,
+        InMemoryEventStore<&'static str, &'static str>,
+        InMemorySnapshotStore<Counter>,
+    > {
+        Repository::new(
+            InMemoryEventStore::default(),
+            InMemorySnapshotStore::default(),
+            0,
+        )
+    }
+
+    #[tokio::test]
+    async fn get_folds_from_version_zero_with_no_snapshot() {
+        let repository = repository();
+        let stream_id = "counter:1";
+
+        repository
+            .event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![
+                    event::Event::from("incremented"),
+                    event::Event::from("incremented"),
+                ],
+            )
+            .await
+            .expect("append should not fail");
+
+        let (state, version) = repository.get(&stream_id).await.expect("get should not fail");
+
+        assert_eq!(Counter(2), state);
+        assert_eq!(Version(2), version);
+    }
+
+    #[tokio::test]
+    async fn get_resumes_from_a_fresh_snapshot_folding_only_the_tail() {
+        let repository = repository();
+        let stream_id = "counter:1";
+
+        repository
+            .event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![
+                    event::Event::from("incremented"),
+                    event::Event::from("incremented"),
+                    event::Event::from("incremented"),
+                ],
+            )
+            .await
+            .expect("append should not fail");
+
+        repository
+            .snapshot_store
+            .save(&stream_id, Counter(2), Version(2))
+            .await
+            .expect("save should not fail");
+
+        let (state, version) = repository.get(&stream_id).await.expect("get should not fail");
+
+        assert_eq!(Counter(3), state);
+        assert_eq!(Version(3), version);
+    }
+
+    #[tokio::test]
+    async fn get_discards_a_stale_snapshot_and_folds_from_scratch() {
+        let repository = repository();
+        let stream_id = "counter:1";
+
+        repository
+            .event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![
+                    event::Event::from("incremented"),
+                    event::Event::from("incremented"),
+                ],
+            )
+            .await
+            .expect("append should not fail");
+
+        // Simulates the event store having been reset independently of
+        // the snapshot store: the snapshot claims a version the stream
+        // never actually reached.
+        repository
+            .snapshot_store
+            .save(&stream_id, Counter(5), Version(5))
+            .await
+            .expect("save should not fail");
+
+        let (state, version) = repository.get(&stream_id).await.expect("get should not fail");
+
+        assert_eq!(Counter(2), state);
+        assert_eq!(Version(2), version);
+    }
+
+    #[tokio::test]
+    async fn save_snapshots_when_a_batch_crosses_the_snapshot_every_boundary_without_landing_on_it(
+    ) {
+        let repository = Repository::new(
+            InMemoryEventStore::<&'static str, &'static str>::default(),
+            InMemorySnapshotStore::default(),
+            10,
+        );
+        let stream_id = "counter:1";
+
+        // A batch of 7 events never lands on an exact multiple of 10
+        // (versions 7, 14, ...), but the second batch still crosses the
+        // boundary at version 10.
+        let batch = || vec![event::Event::from("incremented"); 7];
+
+        repository
+            .save(&stream_id, Version(0), batch(), Counter(7))
+            .await
+            .expect("save should not fail");
+
+        let snapshot = repository
+            .snapshot_store
+            .get(&stream_id)
+            .await
+            .expect("get should not fail");
+
+        assert_eq!(None, snapshot, "version 7 hasn't crossed the boundary yet");
+
+        repository
+            .save(&stream_id, Version(7), batch(), Counter(14))
+            .await
+            .expect("save should not fail");
+
+        let snapshot = repository
+            .snapshot_store
+            .get(&stream_id)
+            .await
+            .expect("get should not fail");
+
+        assert_eq!(Some((Counter(14), Version(14))), snapshot);
+    }
+}
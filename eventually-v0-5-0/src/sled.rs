@@ -0,0 +1,435 @@
+//! A persistent [`event::Store`] implementation backed by `sled`, so
+//! events survive process restarts.
+
+use std::{
+    fmt::Display,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use futures::stream::{iter, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    event::{self, Events, Persisted, PersistedEvents},
+    version::{ConflictError, Version},
+};
+
+/// Error returned by [`SledEventStore`] read operations (`stream`,
+/// `stream_all`), and wrapped by [`AppendError`] on the write path.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("sled storage error: {0}")]
+    Sled(#[from] ::sled::Error),
+
+    #[error("failed to (de)serialize a persisted event: {0}")]
+    Codec(#[from] bincode::Error),
+}
+
+impl From<::sled::transaction::TransactionError<Error>> for Error {
+    fn from(err: ::sled::transaction::TransactionError<Error>) -> Self {
+        match err {
+            ::sled::transaction::TransactionError::Abort(err) => err,
+            ::sled::transaction::TransactionError::Storage(err) => Error::Sled(err),
+        }
+    }
+}
+
+/// Error returned by [`SledEventStore`]'s append-path operations
+/// (`append`, `remove`): either an optimistic concurrency conflict, or an
+/// underlying storage/serialization failure.
+#[derive(Debug, thiserror::Error)]
+pub enum AppendError {
+    #[error(transparent)]
+    Conflict(#[from] ConflictError),
+
+    #[error(transparent)]
+    Store(#[from] Error),
+}
+
+/// A [`event::Store`] implementation backed by a `sled` embedded database.
+///
+/// Events are kept in two keyspaces (`sled` trees): `by_stream`, keyed by
+/// `(stream_id, version)` for per-stream reads, and `by_sequence`, keyed
+/// by the global sequence number, for [`event::Store::stream_all`].
+#[derive(Clone)]
+pub struct SledEventStore<Id, Evt> {
+    by_stream: ::sled::Tree,
+    by_sequence: ::sled::Tree,
+    global_offset: Arc<AtomicU64>,
+    // sled trees are internally concurrent, but the append path needs to
+    // read-then-write the stream's last version and the global offset as
+    // one logical step, so it's serialized the same way
+    // `InMemoryEventStore` serializes it behind a single lock.
+    append_lock: Arc<Mutex<()>>,
+    _marker: PhantomData<(Id, Evt)>,
+}
+
+impl<Id, Evt> SledEventStore<Id, Evt> {
+    /// Opens a `SledEventStore` on the `"events_by_stream"` and
+    /// `"events_by_sequence"` trees of `db`, recovering the global
+    /// sequence counter from whatever was already persisted.
+    pub fn new(db: &::sled::Db) -> Result<Self, Error> {
+        let by_stream = db.open_tree("events_by_stream")?;
+        let by_sequence = db.open_tree("events_by_sequence")?;
+
+        let global_offset = by_sequence
+            .last()?
+            .map(|(key, _)| {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(&key);
+                u64::from_be_bytes(buf)
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            by_stream,
+            by_sequence,
+            global_offset: Arc::new(AtomicU64::new(global_offset)),
+            append_lock: Arc::new(Mutex::new(())),
+            _marker: PhantomData,
+        })
+    }
+}
+
+fn stream_key_prefix(stream_id: &str) -> Vec<u8> {
+    let mut prefix = stream_id.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+fn stream_key(stream_id: &str, version: Version) -> Vec<u8> {
+    let mut key = stream_key_prefix(stream_id);
+    key.extend_from_slice(&version.0.to_be_bytes());
+    key
+}
+
+fn sequence_key(global_sequence: u64) -> [u8; 8] {
+    global_sequence.to_be_bytes()
+}
+
+#[async_trait]
+impl<Id, Evt> event::Store for SledEventStore<Id, Evt>
+where
+    Id: Clone + Display + Serialize + DeserializeOwned + Send + Sync,
+    Evt: Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    type StreamId = Id;
+    type Event = Evt;
+    type StreamError = Error;
+    type AppendError = AppendError;
+
+    fn stream(
+        &self,
+        id: &Self::StreamId,
+        select: event::VersionSelect,
+    ) -> event::Stream<Self::StreamId, Self::Event, Self::StreamError> {
+        let from_version = match select {
+            event::VersionSelect::All => Version::default(),
+            event::VersionSelect::From(v) => v,
+        };
+
+        let events: Result<Vec<Persisted<Id, Evt>>, Error> = self
+            .by_stream
+            .scan_prefix(stream_key_prefix(&id.to_string()))
+            .map(|entry| {
+                let (_, value) = entry?;
+                Ok(bincode::deserialize(&value)?)
+            })
+            .filter(|result: &Result<Persisted<Id, Evt>, Error>| {
+                result.as_ref().map_or(true, |evt| evt.version >= from_version)
+            })
+            .collect();
+
+        match events {
+            Ok(events) => iter(events).map(Ok).boxed(),
+            Err(err) => iter(vec![Err(err)]).boxed(),
+        }
+    }
+
+    fn stream_all(
+        &self,
+        select: event::VersionSelect,
+    ) -> event::Stream<Self::StreamId, Self::Event, Self::StreamError> {
+        let from_sequence = match select {
+            event::VersionSelect::All => 0,
+            event::VersionSelect::From(v) => v.0,
+        };
+
+        let events: Result<Vec<Persisted<Id, Evt>>, Error> = self
+            .by_sequence
+            .range(sequence_key(from_sequence)..)
+            .map(|entry| {
+                let (_, value) = entry?;
+                Ok(bincode::deserialize(&value)?)
+            })
+            .collect();
+
+        match events {
+            Ok(events) => iter(events).map(Ok).boxed(),
+            Err(err) => iter(vec![Err(err)]).boxed(),
+        }
+    }
+
+    async fn append(
+        &self,
+        id: Self::StreamId,
+        version_check: event::StreamVersionExpected,
+        events: Events<Self::Event>,
+    ) -> Result<Version, Self::AppendError> {
+        let _append_guard = self.append_lock.lock().expect("acquire append lock");
+
+        let last_event_stream_version = self
+            .by_stream
+            .scan_prefix(stream_key_prefix(&id.to_string()))
+            .last()
+            .transpose()
+            .map_err(Error::from)?
+            .map(|(_, value)| bincode::deserialize::<Persisted<Id, Evt>>(&value))
+            .transpose()
+            .map_err(Error::from)?
+            .map(|persisted| persisted.version)
+            .unwrap_or_default();
+
+        if let event::StreamVersionExpected::Exact(expected_event_stream_version) = version_check {
+            if last_event_stream_version != expected_event_stream_version {
+                return Err(AppendError::Conflict(ConflictError {
+                    expected: expected_event_stream_version,
+                    actual: last_event_stream_version,
+                }));
+            }
+        }
+
+        let persisted_events: PersistedEvents<Id, Evt> = events
+            .into_iter()
+            .enumerate()
+            .map(|(i, evt)| event::Persisted {
+                stream_id: id.clone(),
+                version: last_event_stream_version + (i as u64) + 1,
+                global_sequence: self.global_offset.fetch_add(1, Ordering::SeqCst) + 1,
+                inner: evt,
+            })
+            .collect();
+
+        // `unwrap_or(last_event_stream_version)`, not `unwrap_or_default`:
+        // an empty `events` batch (a legal no-op append) must leave the
+        // stream's recorded version where it was, not reset it to 0.
+        let new_last_event_stream_version = persisted_events
+            .last()
+            .map(|evt| evt.version)
+            .unwrap_or(last_event_stream_version);
+
+        let stream_id_string = id.to_string();
+
+        (&self.by_stream, &self.by_sequence)
+            .transaction(|(by_stream, by_sequence)| {
+                for persisted in &persisted_events {
+                    let value = bincode::serialize(persisted).map_err(|err| {
+                        ::sled::transaction::ConflictableTransactionError::Abort(Error::from(err))
+                    })?;
+
+                    by_stream.insert(stream_key(&stream_id_string, persisted.version), value.clone())?;
+                    by_sequence.insert(&sequence_key(persisted.global_sequence), value)?;
+                }
+                Ok(())
+            })
+            .map_err(Error::from)?;
+
+        Ok(new_last_event_stream_version)
+    }
+
+    async fn remove(&self, id: &Self::StreamId) -> Result<(), Self::AppendError> {
+        let _append_guard = self.append_lock.lock().expect("acquire append lock");
+
+        let entries: Vec<(::sled::IVec, u64)> = self
+            .by_stream
+            .scan_prefix(stream_key_prefix(&id.to_string()))
+            .map(|entry| {
+                let (key, value) = entry.map_err(Error::from)?;
+                let persisted: Persisted<Id, Evt> =
+                    bincode::deserialize(&value).map_err(Error::from)?;
+                Ok::<_, Error>((key, persisted.global_sequence))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        (&self.by_stream, &self.by_sequence)
+            .transaction(|(by_stream, by_sequence)| {
+                for (key, global_sequence) in &entries {
+                    by_stream.remove(key.as_ref())?;
+                    by_sequence.remove(&sequence_key(*global_sequence))?;
+                }
+                Ok(())
+            })
+            .map_err(Error::from)?;
+
+        Ok(())
+    }
+
+    async fn truncate(
+        &self,
+        id: &Self::StreamId,
+        before: Version,
+    ) -> Result<(), event::TruncateError> {
+        // `Store::truncate` is fixed to `TruncateError`, which has no room
+        // for a storage failure, so reads and writes here still fall back
+        // to `.expect()`.
+        let entries: Vec<(::sled::IVec, Persisted<Id, Evt>)> = self
+            .by_stream
+            .scan_prefix(stream_key_prefix(&id.to_string()))
+            .map(|entry| {
+                let (key, value) = entry.expect("reading a stream event should not fail");
+                let persisted: Persisted<Id, Evt> = bincode::deserialize(&value)
+                    .expect("a persisted event should always deserialize");
+                (key, persisted)
+            })
+            .collect();
+
+        let head = entries
+            .iter()
+            .map(|(_, persisted)| persisted.version)
+            .max()
+            .unwrap_or_default();
+
+        if before.0 > head.0 {
+            return Err(event::TruncateError { before, head });
+        }
+
+        let to_remove: Vec<_> = entries
+            .into_iter()
+            .filter(|(_, persisted)| persisted.version < before)
+            .collect();
+
+        (&self.by_stream, &self.by_sequence)
+            .transaction(|(by_stream, by_sequence)| {
+                for (key, persisted) in &to_remove {
+                    by_stream.remove(key.as_ref())?;
+                    by_sequence.remove(&sequence_key(persisted.global_sequence))?;
+                }
+                Ok(())
+            })
+            .expect("truncating sled stream events should not fail");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::TryStreamExt;
+
+    use super::*;
+    use crate::{event::Store, test::conformance, version::Version};
+
+    /// Opens a `SledEventStore` on a temporary, in-process sled database
+    /// that's removed once the test ends -- no external service needed.
+    fn temp_store() -> SledEventStore<&'static str, &'static str> {
+        let db = ::sled::Config::new()
+            .temporary(true)
+            .open()
+            .expect("open a temporary sled db");
+
+        SledEventStore::new(&db).expect("open a SledEventStore")
+    }
+
+    #[tokio::test]
+    async fn it_works() {
+        let event_store = temp_store();
+        let stream_id = "stream:test";
+        let events = vec![
+            event::Event::from("event-1"),
+            event::Event::from("event-2"),
+            event::Event::from("event-3"),
+        ];
+
+        let new_version = event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(0)),
+                events,
+            )
+            .await
+            .expect("append should not fail");
+
+        assert_eq!(Version(3), new_version);
+
+        let event_stream: Vec<_> = event_store
+            .stream(&stream_id, event::VersionSelect::All)
+            .try_collect()
+            .await
+            .expect("opening an event stream should not fail");
+
+        let payloads: Vec<_> = event_stream.iter().map(|evt| evt.inner.payload).collect();
+        assert_eq!(vec!["event-1", "event-2", "event-3"], payloads);
+    }
+
+    #[tokio::test]
+    async fn version_conflict_checks_work_as_expected() {
+        let event_store = temp_store();
+        let stream_id = "stream:test";
+
+        let append_error = event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(3)),
+                vec![event::Event::from("event-1")],
+            )
+            .await
+            .expect_err("the event stream version should be zero");
+
+        assert!(matches!(
+            append_error,
+            AppendError::Conflict(ConflictError {
+                expected: Version(3),
+                actual: Version(0),
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn append_with_no_events_does_not_reset_the_stream_version() {
+        let event_store = temp_store();
+        let stream_id = "stream:test";
+
+        event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![event::Event::from("event-1"), event::Event::from("event-2")],
+            )
+            .await
+            .expect("append should not fail");
+
+        let new_version = event_store
+            .append(stream_id, event::StreamVersionExpected::Exact(Version(2)), vec![])
+            .await
+            .expect("a no-op append should not fail");
+
+        assert_eq!(Version(2), new_version);
+    }
+
+    #[tokio::test]
+    async fn remove_drops_the_stream() {
+        conformance::remove_drops_the_stream(&temp_store(), "stream:test").await;
+    }
+
+    #[tokio::test]
+    async fn truncate_discards_events_older_than_a_version() {
+        conformance::truncate_discards_events_older_than_a_version(&temp_store(), "stream:test")
+            .await;
+    }
+
+    #[tokio::test]
+    async fn truncate_right_after_the_stream_head_is_rejected() {
+        conformance::truncate_right_after_the_stream_head_is_rejected(&temp_store(), "stream:test")
+            .await;
+    }
+
+    #[tokio::test]
+    async fn truncate_past_the_stream_head_is_rejected() {
+        conformance::truncate_past_the_stream_head_is_rejected(&temp_store(), "stream:test").await;
+    }
+}
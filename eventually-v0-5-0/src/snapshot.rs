@@ -0,0 +1,201 @@
+//! Snapshotting support for [`Aggregate`](crate::aggregate::Aggregate)s
+//! whose streams grow too long to fold from version 0 on every load.
+//!
+//! A [`SnapshotStore`] keeps, per stream, the latest known `State` together
+//! with the [`Version`] it was taken at. A repository built on top of
+//! [`event::Store`](crate::event::Store) can then load the snapshot,
+//! open the event stream with `VersionSelect::From(snapshot_version + 1)`,
+//! and fold only the events appended since, instead of the whole history.
+
+use async_trait::async_trait;
+use std::{collections::HashMap, fmt::Display, sync::RwLock};
+
+use crate::version::Version;
+
+/// Storage abstraction for reading and writing the latest `State` snapshot
+/// of an `Aggregate` stream, keyed by stream id.
+///
+/// Implementations keep at most one snapshot per stream: `save` overwrites
+/// whatever was previously stored. Persistent implementations are expected
+/// to add their own `State: Serialize + DeserializeOwned` (or similar)
+/// bounds where they're implemented, since not every caller needs the
+/// snapshotted state to cross a serialization boundary.
+#[async_trait]
+pub trait SnapshotStore<Id, State>
+where
+    Id: Send + Sync,
+    State: Send + Sync,
+{
+    /// Error type returned by the snapshot store operations.
+    type Error;
+
+    /// Returns the latest snapshot taken for the stream `id`, if any,
+    /// together with the stream [`Version`] it was taken at.
+    async fn get(&self, id: &Id) -> Result<Option<(State, Version)>, Self::Error>;
+
+    /// Persists `state` as the new snapshot for `id`, taken at `version`.
+    async fn save(&self, id: &Id, state: State, version: Version) -> Result<(), Self::Error>;
+}
+
+/// Bookkeeping record pairing a loaded snapshot with the stream version it
+/// was taken at and the version of the last event known to be appended to
+/// the stream.
+///
+/// A repository assembles this after calling [`SnapshotStore::get`] and
+/// checking the event store's current stream version, so it can detect a
+/// stale snapshot -- one claiming to be newer than the stream actually is
+/// -- and skip it rather than fold from an inconsistent starting point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotRecord<State> {
+    pub state: State,
+    pub snapshot_version: Version,
+    pub last_event_version: Version,
+}
+
+impl<State> SnapshotRecord<State> {
+    /// A snapshot is stale if it claims to be more recent than the last
+    /// event actually known to have been appended to the stream.
+    pub fn is_stale(&self) -> bool {
+        self.snapshot_version > self.last_event_version
+    }
+}
+
+/// Returns whether a repository should write a new snapshot after an
+/// append moved a stream from `old_version` to `new_version`, taking one
+/// every `every` events.
+///
+/// Checks whether the append crossed an `every`-boundary rather than
+/// whether `new_version` is an exact multiple of it: a batch whose size
+/// doesn't evenly divide `every` would otherwise jump straight past every
+/// multiple and never trigger a snapshot.
+///
+/// `every == 0` disables snapshotting entirely.
+pub fn should_snapshot(old_version: Version, new_version: Version, every: u64) -> bool {
+    every > 0 && old_version.0 / every != new_version.0 / every
+}
+
+#[derive(Debug)]
+struct InMemorySnapshotStoreBackend<State> {
+    snapshots: HashMap<String, (State, Version)>,
+}
+
+impl<State> Default for InMemorySnapshotStoreBackend<State> {
+    fn default() -> Self {
+        Self {
+            snapshots: Default::default(),
+        }
+    }
+}
+
+/// An in-memory [`SnapshotStore`] implementation, mirroring
+/// [`InMemoryEventStore`](crate::test::store::InMemoryEventStore).
+///
+/// Useful for tests, or as a reference implementation for persistent
+/// backends.
+#[derive(Debug, Default)]
+pub struct InMemorySnapshotStore<State> {
+    backend: RwLock<InMemorySnapshotStoreBackend<State>>,
+}
+
+#[async_trait]
+impl<Id, State> SnapshotStore<Id, State> for InMemorySnapshotStore<State>
+where
+    Id: Display + Send + Sync,
+    State: Clone + Send + Sync,
+{
+    type Error = std::convert::Infallible;
+
+    async fn get(&self, id: &Id) -> Result<Option<(State, Version)>, Self::Error> {
+        let backend = self
+            .backend
+            .read()
+            .expect("acquire read lock on snapshot store backend");
+
+        Ok(backend.snapshots.get(&id.to_string()).cloned())
+    }
+
+    async fn save(&self, id: &Id, state: State, version: Version) -> Result<(), Self::Error> {
+        let mut backend = self
+            .backend
+            .write()
+            .expect("acquire write lock on snapshot store backend");
+
+        backend.snapshots.insert(id.to_string(), (state, version));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_returns_none_when_no_snapshot_was_ever_saved() {
+        let store = InMemorySnapshotStore::<&'static str>::default();
+
+        let snapshot = store
+            .get(&"stream:test")
+            .await
+            .expect("get should not fail");
+
+        assert_eq!(None, snapshot);
+    }
+
+    #[tokio::test]
+    async fn it_returns_the_latest_saved_snapshot() {
+        let store = InMemorySnapshotStore::<&'static str>::default();
+        let stream_id = "stream:test";
+
+        store
+            .save(&stream_id, "state-v1", Version(1))
+            .await
+            .expect("save should not fail");
+
+        store
+            .save(&stream_id, "state-v2", Version(2))
+            .await
+            .expect("save should not fail");
+
+        let snapshot = store
+            .get(&stream_id)
+            .await
+            .expect("get should not fail");
+
+        assert_eq!(Some(("state-v2", Version(2))), snapshot);
+    }
+
+    #[test]
+    fn snapshot_record_detects_staleness() {
+        let fresh = SnapshotRecord {
+            state: (),
+            snapshot_version: Version(3),
+            last_event_version: Version(5),
+        };
+        assert!(!fresh.is_stale());
+
+        let stale = SnapshotRecord {
+            state: (),
+            snapshot_version: Version(5),
+            last_event_version: Version(3),
+        };
+        assert!(stale.is_stale());
+    }
+
+    #[test]
+    fn should_snapshot_fires_every_n_events() {
+        assert!(!should_snapshot(Version(0), Version(0), 0));
+        assert!(should_snapshot(Version(9), Version(10), 10));
+        assert!(!should_snapshot(Version(10), Version(11), 10));
+    }
+
+    #[test]
+    fn should_snapshot_fires_when_a_batch_crosses_a_boundary_without_landing_on_it() {
+        // A batch of 7 events never lands on an exact multiple of 10, but
+        // most appends still cross one: versions go 0, 7, 14, 21, 28, ...
+        assert!(!should_snapshot(Version(0), Version(7), 10));
+        assert!(should_snapshot(Version(7), Version(14), 10)); // crosses 10
+        assert!(should_snapshot(Version(14), Version(21), 10)); // crosses 20
+        assert!(!should_snapshot(Version(21), Version(28), 10)); // stays in [20, 30)
+    }
+}
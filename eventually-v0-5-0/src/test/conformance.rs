@@ -0,0 +1,161 @@
+//! Shared [`event::Store`] conformance tests, run against each backend's
+//! own store construction.
+//!
+//! `InMemoryEventStore`, `SledEventStore` and `PgEventStore` previously
+//! each carried their own copy of these test bodies; a fix to one (e.g.
+//! the `before.0 > head.0` off-by-one on `truncate`) had to be applied to
+//! all three independently, with nothing to stop them drifting apart.
+//! Keeping the assertions here means every backend exercises the exact
+//! same behavior, and a fix only has to land once.
+
+use futures::TryStreamExt;
+
+use crate::{event, version::Version};
+
+/// Asserts `remove` drops every event of the stream, from both the
+/// per-stream read and the global one.
+pub async fn remove_drops_the_stream<S>(store: &S, stream_id: S::StreamId)
+where
+    S: event::Store<Event = &'static str>,
+    S::StreamId: Clone,
+    S::AppendError: std::fmt::Debug,
+    S::StreamError: std::fmt::Debug,
+{
+    store
+        .append(
+            stream_id.clone(),
+            event::StreamVersionExpected::Exact(Version(0)),
+            vec![event::Event::from("event-1")],
+        )
+        .await
+        .expect("append should not fail");
+
+    store
+        .remove(&stream_id)
+        .await
+        .expect("remove should not fail");
+
+    let event_stream: Vec<_> = store
+        .stream(&stream_id, event::VersionSelect::All)
+        .try_collect()
+        .await
+        .expect("opening an event stream should not fail");
+
+    assert!(event_stream.is_empty());
+
+    let all_events: Vec<_> = store
+        .stream_all(event::VersionSelect::All)
+        .try_collect()
+        .await
+        .expect("opening the global stream should not fail");
+
+    assert!(all_events.is_empty());
+}
+
+/// Asserts `truncate` discards events older than the given version,
+/// keeping everything from it onwards.
+pub async fn truncate_discards_events_older_than_a_version<S>(store: &S, stream_id: S::StreamId)
+where
+    S: event::Store<Event = &'static str>,
+    S::StreamId: Clone,
+    S::AppendError: std::fmt::Debug,
+    S::StreamError: std::fmt::Debug,
+{
+    store
+        .append(
+            stream_id.clone(),
+            event::StreamVersionExpected::Exact(Version(0)),
+            vec![
+                event::Event::from("event-1"),
+                event::Event::from("event-2"),
+                event::Event::from("event-3"),
+            ],
+        )
+        .await
+        .expect("append should not fail");
+
+    store
+        .truncate(&stream_id, Version(3))
+        .await
+        .expect("truncate should not fail");
+
+    let event_stream: Vec<_> = store
+        .stream(&stream_id, event::VersionSelect::All)
+        .try_collect()
+        .await
+        .expect("opening an event stream should not fail");
+
+    assert_eq!(1, event_stream.len());
+    assert_eq!("event-3", event_stream[0].inner.payload);
+}
+
+/// Asserts truncating at exactly the stream head + 1 is rejected rather
+/// than discarding the head event.
+pub async fn truncate_right_after_the_stream_head_is_rejected<S>(store: &S, stream_id: S::StreamId)
+where
+    S: event::Store<Event = &'static str>,
+    S::StreamId: Clone,
+    S::AppendError: std::fmt::Debug,
+    S::StreamError: std::fmt::Debug,
+{
+    store
+        .append(
+            stream_id.clone(),
+            event::StreamVersionExpected::Exact(Version(0)),
+            vec![event::Event::from("event-1"), event::Event::from("event-2")],
+        )
+        .await
+        .expect("append should not fail");
+
+    let truncate_error = store
+        .truncate(&stream_id, Version(3))
+        .await
+        .expect_err("truncating at head + 1 should fail, not discard the head event");
+
+    assert_eq!(
+        event::TruncateError {
+            before: Version(3),
+            head: Version(2),
+        },
+        truncate_error
+    );
+
+    let event_stream: Vec<_> = store
+        .stream(&stream_id, event::VersionSelect::All)
+        .try_collect()
+        .await
+        .expect("opening an event stream should not fail");
+
+    assert_eq!(2, event_stream.len());
+}
+
+/// Asserts truncating past the stream head is rejected.
+pub async fn truncate_past_the_stream_head_is_rejected<S>(store: &S, stream_id: S::StreamId)
+where
+    S: event::Store<Event = &'static str>,
+    S::StreamId: Clone,
+    S::AppendError: std::fmt::Debug,
+    S::StreamError: std::fmt::Debug,
+{
+    store
+        .append(
+            stream_id.clone(),
+            event::StreamVersionExpected::Exact(Version(0)),
+            vec![event::Event::from("event-1")],
+        )
+        .await
+        .expect("append should not fail");
+
+    let truncate_error = store
+        .truncate(&stream_id, Version(5))
+        .await
+        .expect_err("truncating past the stream head should fail");
+
+    assert_eq!(
+        event::TruncateError {
+            before: Version(5),
+            head: Version(1),
+        },
+        truncate_error
+    );
+}
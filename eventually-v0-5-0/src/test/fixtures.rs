@@ -0,0 +1,25 @@
+//! A minimal [`Aggregate`] used by the `#[cfg(test)]` modules of
+//! [`crate::repository`] and [`crate::command`], so both don't have to
+//! keep their own copy in sync.
+
+use std::convert::Infallible;
+
+use crate::aggregate::Aggregate;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counter(pub u64);
+
+pub struct CounterAggregate;
+
+impl Aggregate for CounterAggregate {
+    type State = Counter;
+    type Event = &'static str;
+    type Error = Infallible;
+
+    fn apply(state: Self::State, event: Self::Event) -> Result<Self::State, Self::Error> {
+        Ok(match event {
+            "incremented" => Counter(state.0 + 1),
+            _ => state,
+        })
+    }
+}
@@ -2,18 +2,33 @@ use std::{
     collections::HashMap,
     convert::Infallible,
     fmt::Display,
-    sync::{atomic::AtomicU64, Arc, RwLock},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
 };
 
 use async_trait::async_trait;
-use futures::stream::{iter, StreamExt};
+use futures::{
+    future::ready,
+    stream::{iter, StreamExt},
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
 
 use crate::{
     event,
     event::{Events, PersistedEvents},
+    subscription,
     version::{ConflictError, Version},
 };
 
+/// Capacity of the live broadcast channel backing
+/// [`subscription::Subscription`] for [`InMemoryEventStore`]. Subscribers
+/// that fall more than this many events behind the latest append miss
+/// events and get a `Lagged` error on their next read.
+const LIVE_SUBSCRIPTION_CAPACITY: usize = 1024;
+
 #[derive(Debug)]
 struct InMemoryEventStoreBackend<Id, Evt> {
     event_streams: HashMap<String, PersistedEvents<Id, Evt>>,
@@ -27,10 +42,29 @@ impl<Id, Evt> Default for InMemoryEventStoreBackend<Id, Evt> {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Clone)]
 pub struct InMemoryEventStore<Id, Evt> {
     global_offset: Arc<AtomicU64>,
     backend: Arc<RwLock<InMemoryEventStoreBackend<Id, Evt>>>,
+    live: broadcast::Sender<event::Persisted<Id, Evt>>,
+}
+
+impl<Id, Evt> std::fmt::Debug for InMemoryEventStore<Id, Evt> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InMemoryEventStore").finish_non_exhaustive()
+    }
+}
+
+impl<Id, Evt> Default for InMemoryEventStore<Id, Evt> {
+    fn default() -> Self {
+        let (live, _) = broadcast::channel(LIVE_SUBSCRIPTION_CAPACITY);
+
+        Self {
+            global_offset: Default::default(),
+            backend: Default::default(),
+            live,
+        }
+    }
 }
 
 #[async_trait]
@@ -68,6 +102,27 @@ where
         iter(events).map(Ok).boxed()
     }
 
+    fn stream_all(
+        &self,
+        select: event::VersionSelect,
+    ) -> event::Stream<Self::StreamId, Self::Event, Self::StreamError> {
+        let backend = self
+            .backend
+            .read()
+            .expect("acquire read lock on event store backend");
+
+        let mut events: Vec<_> = backend.event_streams.values().flatten().cloned().collect();
+
+        events.sort_by_key(|evt| evt.global_sequence);
+
+        let events = events.into_iter().filter(move |evt| match select {
+            event::VersionSelect::All => true,
+            event::VersionSelect::From(v) => evt.global_sequence >= v.0,
+        });
+
+        iter(events).map(Ok).boxed()
+    }
+
     async fn append(
         &self,
         id: Self::StreamId,
@@ -100,10 +155,10 @@ where
         let mut persisted_events: PersistedEvents<Id, Evt> = events
             .into_iter()
             .enumerate()
-            // TODO: add sequence number
             .map(|(i, evt)| event::Persisted {
                 stream_id: id.clone(),
                 version: last_event_stream_version + (i as u64) + 1,
+                global_sequence: self.global_offset.fetch_add(1, Ordering::SeqCst) + 1,
                 inner: evt,
             })
             .collect();
@@ -111,7 +166,14 @@ where
         let new_last_event_stream_version = persisted_events
             .last()
             .map(|evt| evt.version)
-            .unwrap_or_default();
+            .unwrap_or(last_event_stream_version);
+
+        for evt in &persisted_events {
+            // A send error just means there are no live subscribers right
+            // now; catch-up readers will still pick this event up from
+            // the backend.
+            let _ = self.live.send(evt.clone());
+        }
 
         backend
             .event_streams
@@ -121,6 +183,97 @@ where
 
         Ok(new_last_event_stream_version)
     }
+
+    async fn remove(&self, id: &Self::StreamId) -> Result<(), Self::AppendError> {
+        let mut backend = self
+            .backend
+            .write()
+            .expect("acquire write lock on event store backend");
+
+        backend.event_streams.remove(&id.to_string());
+
+        Ok(())
+    }
+
+    async fn truncate(
+        &self,
+        id: &Self::StreamId,
+        before: Version,
+    ) -> Result<(), event::TruncateError> {
+        let mut backend = self
+            .backend
+            .write()
+            .expect("acquire write lock on event store backend");
+
+        let key = id.to_string();
+
+        let head = backend
+            .event_streams
+            .get(&key)
+            .and_then(|events| events.last())
+            .map(|evt| evt.version)
+            .unwrap_or_default();
+
+        if before.0 > head.0 {
+            return Err(event::TruncateError { before, head });
+        }
+
+        if let Some(events) = backend.event_streams.get_mut(&key) {
+            events.retain(|evt| evt.version >= before);
+        }
+
+        Ok(())
+    }
+}
+
+impl<Id, Evt> subscription::Subscription for InMemoryEventStore<Id, Evt>
+where
+    Id: Clone + Display + Send + Sync + 'static,
+    Evt: Clone + Send + Sync + 'static,
+{
+    type StreamId = Id;
+    type Event = Evt;
+    type Error = tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+
+    fn subscribe(
+        &self,
+        from_global_sequence: u64,
+    ) -> event::Stream<Self::StreamId, Self::Event, Self::Error> {
+        // Subscribe to live events *before* reading the catch-up backlog,
+        // so no event appended in between is missed. That same window
+        // means an event appended between the two reads could be
+        // delivered by both, so the live stream is filtered down to
+        // sequences past the last one the catch-up read already
+        // delivered.
+        let live = self.live.subscribe();
+
+        let catch_up: Vec<_> = {
+            let backend = self
+                .backend
+                .read()
+                .expect("acquire read lock on event store backend");
+
+            let mut events: Vec<_> = backend.event_streams.values().flatten().cloned().collect();
+            events.sort_by_key(|evt| evt.global_sequence);
+            events.retain(|evt| evt.global_sequence >= from_global_sequence);
+            events
+        };
+
+        let last_caught_up_sequence = catch_up
+            .last()
+            .map(|evt| evt.global_sequence)
+            .unwrap_or(from_global_sequence.saturating_sub(1));
+
+        let live = BroadcastStream::new(live).filter(move |result| {
+            let keep = match result {
+                Ok(evt) => evt.global_sequence > last_caught_up_sequence,
+                Err(_) => true,
+            };
+            ready(keep)
+        });
+
+        iter(catch_up).map(Ok).chain(live).boxed()
+    }
 }
 
 #[cfg(test)]
@@ -128,7 +281,10 @@ mod test {
     use futures::TryStreamExt;
 
     use super::*;
-    use crate::{event, event::Store, version, version::Version};
+    use crate::{
+        event, event::Store, subscription::Subscription, test::conformance, version,
+        version::Version,
+    };
 
     #[tokio::test]
     async fn it_works() {
@@ -159,6 +315,7 @@ mod test {
             .map(|(i, evt)| event::Persisted {
                 stream_id,
                 version: Version((i as u64) + 1),
+                global_sequence: (i as u64) + 1,
                 inner: evt,
             })
             .collect::<Vec<_>>();
@@ -200,4 +357,171 @@ mod test {
             append_error
         );
     }
+
+    #[tokio::test]
+    async fn stream_all_orders_events_by_global_sequence_across_streams() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+
+        event_store
+            .append(
+                "stream:a",
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![event::Event::from("a-1"), event::Event::from("a-2")],
+            )
+            .await
+            .expect("append should not fail");
+
+        event_store
+            .append(
+                "stream:b",
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![event::Event::from("b-1")],
+            )
+            .await
+            .expect("append should not fail");
+
+        let all_events: Vec<_> = event_store
+            .stream_all(event::VersionSelect::All)
+            .try_collect()
+            .await
+            .expect("opening the global stream should not fail");
+
+        let global_sequences: Vec<_> = all_events.iter().map(|evt| evt.global_sequence).collect();
+        assert_eq!(vec![1, 2, 3], global_sequences);
+
+        let from_second: Vec<_> = event_store
+            .stream_all(event::VersionSelect::From(Version(2)))
+            .try_collect()
+            .await
+            .expect("opening the global stream should not fail");
+
+        assert_eq!(2, from_second.len());
+        assert_eq!("b-1", from_second[1].inner.payload);
+    }
+
+    #[tokio::test]
+    async fn subscribe_delivers_catch_up_then_live_events() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+
+        event_store
+            .append(
+                "stream:test",
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![event::Event::from("past-event")],
+            )
+            .await
+            .expect("append should not fail");
+
+        let mut subscription = event_store.subscribe(0).boxed();
+
+        let caught_up = subscription
+            .try_next()
+            .await
+            .expect("catch-up read should not fail")
+            .expect("catch-up event should be delivered");
+        assert_eq!("past-event", caught_up.inner.payload);
+
+        event_store
+            .append(
+                "stream:test",
+                event::StreamVersionExpected::Exact(Version(1)),
+                vec![event::Event::from("live-event")],
+            )
+            .await
+            .expect("append should not fail");
+
+        let delivered_live = subscription
+            .try_next()
+            .await
+            .expect("live read should not fail")
+            .expect("live event should be delivered");
+        assert_eq!("live-event", delivered_live.inner.payload);
+    }
+
+    #[tokio::test]
+    async fn subscribe_does_not_redeliver_an_event_already_seen_in_the_catch_up_read() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+        let stream_id = "stream:test";
+
+        event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(0)),
+                vec![event::Event::from("event-1")],
+            )
+            .await
+            .expect("append should not fail");
+
+        let mut subscription = event_store.subscribe(0).boxed();
+
+        // `append` always broadcasts on `live` too, so "event-1" was
+        // already sent on the channel before this subscription was
+        // taken out. Re-send it here to simulate the race the dedup
+        // filter guards against: an event appended in the window
+        // between `subscribe`'s live subscription and its catch-up
+        // read, which both paths would otherwise deliver.
+        let event_1 = event_store
+            .stream(&stream_id, event::VersionSelect::All)
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("opening an event stream should not fail")
+            .remove(0);
+        let _ = event_store.live.send(event_1);
+
+        let caught_up = subscription
+            .try_next()
+            .await
+            .expect("catch-up read should not fail")
+            .expect("catch-up event should be delivered");
+        assert_eq!("event-1", caught_up.inner.payload);
+
+        event_store
+            .append(
+                stream_id,
+                event::StreamVersionExpected::Exact(Version(1)),
+                vec![event::Event::from("event-2")],
+            )
+            .await
+            .expect("append should not fail");
+
+        // The duplicate of "event-1" must have been filtered out: the
+        // next event off the subscription is "event-2", not a second
+        // copy of "event-1".
+        let next = subscription
+            .try_next()
+            .await
+            .expect("live read should not fail")
+            .expect("an event should be delivered");
+        assert_eq!("event-2", next.inner.payload);
+    }
+
+    #[tokio::test]
+    async fn remove_drops_the_stream() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+
+        conformance::remove_drops_the_stream(&event_store, "stream:test").await;
+    }
+
+    #[tokio::test]
+    async fn truncate_discards_events_older_than_a_version() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+
+        conformance::truncate_discards_events_older_than_a_version(&event_store, "stream:test")
+            .await;
+    }
+
+    #[tokio::test]
+    async fn truncate_right_after_the_stream_head_is_rejected() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+
+        conformance::truncate_right_after_the_stream_head_is_rejected(&event_store, "stream:test")
+            .await;
+    }
+
+    #[tokio::test]
+    async fn truncate_past_the_stream_head_is_rejected() {
+        let event_store = InMemoryEventStore::<&'static str, &'static str>::default();
+
+        conformance::truncate_past_the_stream_head_is_rejected(&event_store, "stream:test").await;
+    }
 }
\ No newline at end of file
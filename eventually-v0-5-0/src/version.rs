@@ -0,0 +1,39 @@
+use std::fmt;
+use std::ops::Add;
+
+/// The version of an event stream, i.e. the number of events that have
+/// been appended to it so far.
+///
+/// `Version(0)` represents a stream that has not been created yet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Version(pub u64);
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for Version {
+    fn from(version: u64) -> Self {
+        Self(version)
+    }
+}
+
+impl Add<u64> for Version {
+    type Output = Version;
+
+    fn add(self, rhs: u64) -> Self::Output {
+        Version(self.0 + rhs)
+    }
+}
+
+/// Error returned by [`event::Store::append`](crate::event::Store::append)
+/// when the optimistic concurrency check on the stream version fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("stream version conflict: expected {expected}, got {actual}")]
+pub struct ConflictError {
+    pub expected: Version,
+    pub actual: Version,
+}